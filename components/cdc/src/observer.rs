@@ -1,6 +1,12 @@
 // Copyright 2020 TiKV Project Authors. Licensed under Apache-2.0.
 
-use std::sync::{Arc, RwLock};
+use std::{
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
 use collections::HashMap;
 use engine_traits::KvEngine;
@@ -17,6 +23,83 @@ use crate::{
     old_value::{self, OldValueCache},
 };
 
+/// Number of shards `RegionRegistry` splits its map into by default. Must
+/// be a power of two so indexing by `region_id & (N - 1)` stays a cheap
+/// mask instead of a modulo.
+const DEFAULT_REGION_REGISTRY_SHARDS: usize = 256;
+
+/// Sharded replacement for a single `RwLock<HashMap<u64, ObserveId>>`.
+/// `is_subscribed` only takes a read lock on the one shard `region_id`
+/// hashes into, and `subscribe_region`/`unsubscribe_region` only
+/// write-lock that shard, so lock contention no longer scales with the
+/// total number of observed regions.
+struct RegionRegistry {
+    shards: Vec<RwLock<HashMap<u64, ObserveId>>>,
+    mask: u64,
+}
+
+impl RegionRegistry {
+    fn new(shard_count: usize) -> Self {
+        assert!(
+            shard_count.is_power_of_two(),
+            "shard_count must be a power of two, got {}",
+            shard_count
+        );
+        RegionRegistry {
+            shards: (0..shard_count)
+                .map(|_| RwLock::new(HashMap::default()))
+                .collect(),
+            mask: shard_count as u64 - 1,
+        }
+    }
+
+    fn shard(&self, region_id: u64) -> &RwLock<HashMap<u64, ObserveId>> {
+        &self.shards[(region_id & self.mask) as usize]
+    }
+
+    fn insert(&self, region_id: u64, observe_id: ObserveId) -> Option<ObserveId> {
+        self.shard(region_id)
+            .write()
+            .unwrap()
+            .insert(region_id, observe_id)
+    }
+
+    fn remove_if_matches(&self, region_id: u64, observe_id: ObserveId) -> Option<ObserveId> {
+        let mut shard = self.shard(region_id).write().unwrap();
+        // To avoid ABA problem, we must check the unique ObserveId.
+        if let Some(oid) = shard.get(&region_id) {
+            if *oid == observe_id {
+                return shard.remove(&region_id);
+            }
+        }
+        None
+    }
+
+    fn get(&self, region_id: u64) -> Option<ObserveId> {
+        self.shard(region_id).read().unwrap().get(&region_id).cloned()
+    }
+}
+
+impl Default for RegionRegistry {
+    fn default() -> Self {
+        RegionRegistry::new(DEFAULT_REGION_REGISTRY_SHARDS)
+    }
+}
+
+/// What `CdcObserver` does with an applied command batch when admitting it
+/// would push `memory_quota` past its budget, instead of forcing the
+/// allocation through unconditionally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaExceededPolicy {
+    /// Deregister every region in the over-budget batch with a distinct
+    /// "congested" error so downstream can re-initialize once memory frees
+    /// up, and drop the batch instead of forcing the allocation.
+    DropRegion,
+    /// Briefly retry the allocation for up to `timeout` before falling back
+    /// to [`DropRegion`](Self::DropRegion).
+    Wait { timeout: Duration },
+}
+
 /// An Observer for CDC.
 ///
 /// It observes raftstore internal events, such as:
@@ -26,13 +109,19 @@ use crate::{
 pub struct CdcObserver {
     sched: Scheduler<Task>,
     memory_quota: Arc<MemoryQuota>,
-    // A shared registry for managing observed regions.
-    // TODO: it may become a bottleneck, find a better way to manage the registry.
-    observe_regions: Arc<RwLock<HashMap<u64, ObserveId>>>,
+    // A shared, sharded registry for managing observed regions. See
+    // `RegionRegistry`.
+    observe_regions: Arc<RegionRegistry>,
+    quota_exceeded_policy: QuotaExceededPolicy,
+    // Number of applied command batches that exceeded `memory_quota` and
+    // were handled by `quota_exceeded_policy` instead of being forced
+    // through. Exposed so operators can alert on sustained CDC backpressure.
+    batches_over_quota: Arc<AtomicU64>,
 }
 
 impl CdcObserver {
-    /// Create a new `CdcObserver`.
+    /// Create a new `CdcObserver` with the default shard count and a
+    /// [`QuotaExceededPolicy::DropRegion`] quota policy.
     ///
     /// Events are strong ordered, so `sched` must be implemented as
     /// a FIFO queue.
@@ -41,9 +130,58 @@ impl CdcObserver {
             sched,
             memory_quota,
             observe_regions: Arc::default(),
+            quota_exceeded_policy: QuotaExceededPolicy::DropRegion,
+            batches_over_quota: Arc::default(),
+        }
+    }
+
+    /// Create a new `CdcObserver` whose region registry is split into
+    /// `shard_count` shards (must be a power of two). Use this over [`new`]
+    /// to tune lock contention for deployments with unusually many or few
+    /// observed regions.
+    ///
+    /// [`new`]: Self::new
+    pub fn new_with_shard_count(
+        sched: Scheduler<Task>,
+        memory_quota: Arc<MemoryQuota>,
+        shard_count: usize,
+    ) -> CdcObserver {
+        CdcObserver {
+            sched,
+            memory_quota,
+            observe_regions: Arc::new(RegionRegistry::new(shard_count)),
+            quota_exceeded_policy: QuotaExceededPolicy::DropRegion,
+            batches_over_quota: Arc::default(),
         }
     }
 
+    /// Create a new `CdcObserver` with the default shard count, but a
+    /// caller-chosen policy for what to do when an applied command batch
+    /// would exceed `memory_quota`. Use this over [`new`] to tune how CDC
+    /// degrades under memory pressure instead of forcing the allocation.
+    ///
+    /// [`new`]: Self::new
+    pub fn new_with_quota_policy(
+        sched: Scheduler<Task>,
+        memory_quota: Arc<MemoryQuota>,
+        quota_exceeded_policy: QuotaExceededPolicy,
+    ) -> CdcObserver {
+        CdcObserver {
+            sched,
+            memory_quota,
+            observe_regions: Arc::default(),
+            quota_exceeded_policy,
+            batches_over_quota: Arc::default(),
+        }
+    }
+
+    /// Number of applied command batches whose first allocation attempt
+    /// exceeded `memory_quota`, whether they were then dropped or admitted
+    /// after waiting for quota to free up.
+    pub fn batches_over_quota(&self) -> u64 {
+        self.batches_over_quota.load(Ordering::Relaxed)
+    }
+
     pub fn register_to(&self, coprocessor_host: &mut CoprocessorHost<impl KvEngine>) {
         // use 0 as the priority of the cmd observer. CDC should have a higher priority
         // than the `resolved-ts`'s cmd observer
@@ -63,33 +201,19 @@ impl CdcObserver {
     ///
     /// Return previous ObserveId if there is one.
     pub fn subscribe_region(&self, region_id: u64, observe_id: ObserveId) -> Option<ObserveId> {
-        self.observe_regions
-            .write()
-            .unwrap()
-            .insert(region_id, observe_id)
+        self.observe_regions.insert(region_id, observe_id)
     }
 
     /// Stops observe the region.
     ///
     /// Return ObserverID if unsubscribe successfully.
     pub fn unsubscribe_region(&self, region_id: u64, observe_id: ObserveId) -> Option<ObserveId> {
-        let mut regions = self.observe_regions.write().unwrap();
-        // To avoid ABA problem, we must check the unique ObserveId.
-        if let Some(oid) = regions.get(&region_id) {
-            if *oid == observe_id {
-                return regions.remove(&region_id);
-            }
-        }
-        None
+        self.observe_regions.remove_if_matches(region_id, observe_id)
     }
 
     /// Check whether the region is subscribed or not.
     pub fn is_subscribed(&self, region_id: u64) -> Option<ObserveId> {
-        self.observe_regions
-            .read()
-            .unwrap()
-            .get(&region_id)
-            .cloned()
+        self.observe_regions.get(region_id)
     }
 }
 
@@ -126,11 +250,30 @@ impl<E: KvEngine> CmdObserver<E> for CdcObserver {
                                   query_ts,
                                   old_value_cache: &mut OldValueCache,
                                   statistics: &mut Statistics| {
-            old_value::get_old_value(&snapshot, key, query_ts, old_value_cache, statistics)
+            let value = old_value::get_old_value(&snapshot, key, query_ts, old_value_cache, statistics);
+            // Real call site for `Statistics::maybe_report_progress`: this
+            // closure runs once per key as `MultiBatch` old values are
+            // resolved, so it's a natural place to poll for a progress
+            // callback registered via `set_progress_callback` (a no-op when
+            // none is registered).
+            statistics.maybe_report_progress();
+            value
         };
 
         let size = cmd_batches.iter().map(|b| b.size()).sum();
-        self.memory_quota.alloc_force(size);
+        if let Err(e) = self.memory_quota.alloc(size) {
+            warn!("cdc memory quota exceeded, applying backpressure policy";
+                "size" => size, "policy" => ?self.quota_exceeded_policy, "err" => ?e);
+            // Either outcome here is a backpressure event operators should
+            // be able to alert on, so count both: a batch admitted only after
+            // waiting out (part of) the timeout is still a batch that got
+            // held up by the quota, not a free pass.
+            self.batches_over_quota.fetch_add(1, Ordering::Relaxed);
+            if !self.wait_for_quota(size) {
+                self.deregister_for_quota(&cmd_batches);
+                return;
+            }
+        }
         if let Err(e) = self.sched.schedule(Task::MultiBatch {
             multi: cmd_batches,
             old_value_cb: Box::new(get_old_value),
@@ -142,6 +285,57 @@ impl<E: KvEngine> CmdObserver<E> for CdcObserver {
     fn on_applied_current_term(&self, _: StateRole, _: &Region) {}
 }
 
+impl CdcObserver {
+    /// Applies `quota_exceeded_policy` once `memory_quota` has already
+    /// rejected an allocation of `size` bytes. Returns `true` once the
+    /// allocation succeeds (the caller may proceed), or `false` if the
+    /// batch should be dropped.
+    ///
+    /// This runs on the apply/flush hot path, so it backs off with a capped
+    /// sleep between polls instead of `thread::yield_now()`-spinning: a
+    /// quota that stays exhausted for anywhere near the full `timeout` would
+    /// otherwise pin this thread at 100% CPU the whole time for no useful
+    /// work.
+    fn wait_for_quota(&self, size: usize) -> bool {
+        let QuotaExceededPolicy::Wait { timeout } = self.quota_exceeded_policy else {
+            return false;
+        };
+        const POLL_INTERVAL: Duration = Duration::from_millis(10);
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.memory_quota.alloc(size).is_ok() {
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            std::thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    /// Deregisters every region carried by an over-budget command batch with
+    /// a distinct "congested" error, so downstream can re-initialize once
+    /// memory frees up instead of the batch being forced through.
+    fn deregister_for_quota(&self, cmd_batches: &[CmdBatch]) {
+        for cb in cmd_batches {
+            let region_id = cb.region_id;
+            let observe_id = cb.cdc_id;
+            let store_err = RaftStoreError::Other(
+                format!("cdc region {} dropped: memory quota exceeded", region_id).into(),
+            );
+            let deregister = Deregister::Delegate {
+                region_id,
+                observe_id,
+                err: CdcError::request(store_err.into()),
+            };
+            if let Err(e) = self.sched.schedule(Task::Deregister(deregister)) {
+                error!("cdc schedule cdc task failed"; "error" => ?e);
+            }
+        }
+    }
+}
+
 impl RoleObserver for CdcObserver {
     fn on_role_change(&self, ctx: &mut ObserverContext<'_>, role_change: &RoleChange) {
         if role_change.state != StateRole::Leader {
@@ -357,13 +551,48 @@ mod tests {
     }
 
     #[test]
-    fn test_txn_extra_dropped_since_exceed_memory_quota() {
-        let memory_quota = Arc::new(MemoryQuota::new(10));
-        let (task_sched, mut task_rx) = dummy_scheduler();
-        let observer = CdcObserver::new(task_sched.clone(), memory_quota.clone());
-        let txn_extra_scheduler =
-            CdcTxnExtraScheduler::new(task_sched.clone(), memory_quota.clone());
+    fn test_on_flush_applied_cmd_batch_drops_region_when_quota_exceeded() {
+        let (scheduler, mut rx) = tikv_util::worker::dummy_scheduler();
+        // A quota of 0 bytes means even a single batch can never be
+        // admitted, so the default `QuotaExceededPolicy::DropRegion` kicks
+        // in immediately instead of forcing the allocation through.
+        let memory_quota = Arc::new(MemoryQuota::new(0));
+        let observer = CdcObserver::new(scheduler, memory_quota.clone());
+        let observe_info = CmdObserveInfo::from_handle(
+            ObserveHandle::new(),
+            ObserveHandle::new(),
+            ObserveHandle::new(),
+        );
+        let engine = TestEngineBuilder::new().build().unwrap().get_rocksdb();
+
+        let mut cb = CmdBatch::new(&observe_info, 1);
+        cb.push(&observe_info, 1, Cmd::default());
+        let region_id = cb.region_id;
+        let observe_id = cb.cdc_id;
+        <CdcObserver as CmdObserver<RocksEngine>>::on_flush_applied_cmd_batch(
+            &observer,
+            cb.level,
+            &mut vec![cb],
+            &engine,
+        );
+
+        assert_eq!(memory_quota.in_use(), 0);
+        assert_eq!(observer.batches_over_quota(), 1);
+        match rx.recv_timeout(Duration::from_millis(10)).unwrap().unwrap() {
+            Task::Deregister(Deregister::Delegate {
+                region_id: got_region_id,
+                observe_id: got_observe_id,
+                ..
+            }) => {
+                assert_eq!(got_region_id, region_id);
+                assert_eq!(got_observe_id, observe_id);
+            }
+            _ => panic!("unexpected task"),
+        };
+    }
 
+    #[test]
+    fn test_txn_extra_dropped_since_exceed_memory_quota() {
         let observe_info = CmdObserveInfo::from_handle(
             ObserveHandle::new(),
             ObserveHandle::new(),
@@ -371,6 +600,14 @@ mod tests {
         );
         let mut cb = CmdBatch::new(&observe_info, 0);
         cb.push(&observe_info, 0, Cmd::default());
+        // Size the quota to fit exactly this one batch, so the flush below
+        // succeeds but leaves no room for the txn_extra allocation that
+        // follows.
+        let memory_quota = Arc::new(MemoryQuota::new(cb.size()));
+        let (task_sched, mut task_rx) = dummy_scheduler();
+        let observer = CdcObserver::new(task_sched.clone(), memory_quota.clone());
+        let txn_extra_scheduler =
+            CdcTxnExtraScheduler::new(task_sched.clone(), memory_quota.clone());
 
         let engine = TestEngineBuilder::new().build().unwrap().get_rocksdb();
         <CdcObserver as CmdObserver<RocksEngine>>::on_flush_applied_cmd_batch(
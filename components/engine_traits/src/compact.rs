@@ -2,15 +2,77 @@
 
 //! Functionality related to compaction
 
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
 
 use crate::{CfNamesExt, errors::Result};
 
+/// A cheap, cloneable handle for observing the progress of, and requesting
+/// early cancellation of, a manual compaction. The engine implementation
+/// polls [`is_cancelled`](Self::is_cancelled) between subcompactions and
+/// reports progress via [`add_progress`](Self::add_progress), so a caller
+/// can query [`bytes_processed`](Self::bytes_processed) /
+/// [`files_processed`](Self::files_processed) and call
+/// [`cancel`](Self::cancel) to ask a long-running compaction to stop early,
+/// e.g. because the node has come under load or is being downgraded.
+#[derive(Clone, Debug, Default)]
+pub struct CompactionControl {
+    inner: Arc<CompactionControlInner>,
+}
+
+#[derive(Debug, Default)]
+struct CompactionControlInner {
+    bytes_processed: AtomicU64,
+    files_processed: AtomicU64,
+    cancelled: AtomicBool,
+}
+
+impl CompactionControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request that the compaction this handle is attached to stop as soon
+    /// as the engine implementation next polls `is_cancelled`.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn bytes_processed(&self) -> u64 {
+        self.inner.bytes_processed.load(Ordering::Relaxed)
+    }
+
+    pub fn files_processed(&self) -> u64 {
+        self.inner.files_processed.load(Ordering::Relaxed)
+    }
+
+    /// Called by the engine implementation as each subcompaction completes.
+    /// Cloned handles (e.g. one per column family in `compact_range`) share
+    /// the same counters, so progress aggregates across all of them.
+    pub fn add_progress(&self, bytes: u64, files: u64) {
+        self.inner.bytes_processed.fetch_add(bytes, Ordering::Relaxed);
+        self.inner.files_processed.fetch_add(files, Ordering::Relaxed);
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ManualCompactionOptions {
     pub exclusive_manual: bool,
     pub max_subcompactions: u32,
     pub bottommost_level_force: bool,
+    /// Optional progress/cancellation handle. Cloning `ManualCompactionOptions`
+    /// (e.g. once per column family) shares the same handle, so progress and
+    /// cancellation apply across the whole manual compaction.
+    pub control: Option<CompactionControl>,
 }
 
 impl ManualCompactionOptions {
@@ -23,13 +85,50 @@ impl ManualCompactionOptions {
             exclusive_manual,
             max_subcompactions,
             bottommost_level_force,
+            control: None,
         }
     }
+
+    /// Attach a progress/cancellation handle to this compaction.
+    pub fn with_control(mut self, control: CompactionControl) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.control.as_ref().is_some_and(CompactionControl::is_cancelled)
+    }
 }
 
 pub trait CompactExt: CfNamesExt {
     type CompactedEvent: CompactedEvent;
 
+    /// Registry of subscribers to this engine's `CompactedEvent`s. The
+    /// implementor is responsible for feeding it every `CompactedEvent` it
+    /// produces via [`CompactionWatcherRegistry::dispatch`].
+    ///
+    /// Required rather than defaulted: an address-keyed global cache was
+    /// tried here first, but `CompactExt` engines are routinely created and
+    /// dropped within one process (this crate's own tests build a fresh
+    /// engine per test), so a later engine reusing a freed address would
+    /// silently inherit a dead engine's leaked registry. There's no way to
+    /// fake per-instance storage safely without the instance actually owning
+    /// it, so each implementor needs a real `CompactionWatcherRegistry`
+    /// field (a `#[derive(Default)]`-friendly one works fine, since the
+    /// registry itself is `Default`).
+    fn compaction_watcher_registry(&self) -> &CompactionWatcherRegistry;
+
+    /// Subscribe `watcher` to every `CompactedEvent` this engine produces,
+    /// fanned out by the engine's single background dispatch thread instead
+    /// of `watcher`'s subsystem wiring its own coprocessor hook.
+    ///
+    /// `watcher` is held weakly: once it can no longer be upgraded it is
+    /// pruned automatically on the next dispatch, so subscribers don't need
+    /// to explicitly unregister before being dropped.
+    fn register_compaction_watcher(&self, name: impl Into<String>, watcher: Weak<dyn CompactionWatcher>) {
+        self.compaction_watcher_registry().register(name, watcher);
+    }
+
     /// Checks whether any column family sets `disable_auto_compactions` to
     /// `True` or not.
     fn auto_compactions_is_disabled(&self) -> Result<bool>;
@@ -40,7 +139,13 @@ pub trait CompactExt: CfNamesExt {
         end_key: Option<&[u8]>,
         compaction_option: ManualCompactionOptions,
     ) -> Result<()> {
+        // `compaction_option.control`, if set, is shared by every clone
+        // below, so progress naturally aggregates across column families
+        // and a cancellation requested mid-way stops the remaining ones.
         for cf in self.cf_names() {
+            if compaction_option.is_cancelled() {
+                break;
+            }
             self.compact_range_cf(cf, start_key, end_key, compaction_option.clone())?;
         }
         Ok(())
@@ -112,3 +217,85 @@ pub trait CompactedEvent: Send {
 
     fn cf(&self) -> &str;
 }
+
+/// A subscriber to `CompactedEvent`s produced by a `CompactExt` engine.
+///
+/// Implementors are held weakly by `CompactionWatcherRegistry`, so a
+/// subscriber that is dropped without unregistering is pruned automatically
+/// instead of leaking a registration forever.
+pub trait CompactionWatcher: Send + Sync {
+    fn on_compacted(&self, name: &str, digest: &CompactionDigest);
+}
+
+/// The result of evaluating a `CompactedEvent` once, shared by every
+/// registered `CompactionWatcher` instead of each subsystem (split-check,
+/// resolved-ts, CDC, PITR, ...) re-deriving it from its own copy of the
+/// event.
+#[derive(Debug, Clone)]
+pub struct CompactionDigest {
+    pub cf: String,
+    pub output_level_label: String,
+    pub total_bytes_declined: u64,
+    pub is_size_declining_trivial: bool,
+    pub ranges_declined_bytes: Vec<(u64, u64)>,
+}
+
+impl CompactionDigest {
+    /// Evaluates `event` exactly once, consuming it, so every watcher can
+    /// share the same digest instead of each calling
+    /// `calc_ranges_declined_bytes` (which takes `self` by value) on its own
+    /// copy of the event.
+    pub fn from_event<E: CompactedEvent>(
+        event: E,
+        split_check_diff: u64,
+        ranges: &BTreeMap<Vec<u8>, u64>,
+        bytes_threshold: u64,
+    ) -> Self {
+        let cf = event.cf().to_owned();
+        let output_level_label = event.output_level_label();
+        let total_bytes_declined = event.total_bytes_declined();
+        let is_size_declining_trivial = event.is_size_declining_trivial(split_check_diff);
+        let ranges_declined_bytes = event.calc_ranges_declined_bytes(ranges, bytes_threshold);
+        CompactionDigest {
+            cf,
+            output_level_label,
+            total_bytes_declined,
+            is_size_declining_trivial,
+            ranges_declined_bytes,
+        }
+    }
+}
+
+/// A registry of `CompactionWatcher`s fanned out to by a single background
+/// dispatch thread, instead of each consumer wiring its own coprocessor hook
+/// onto the engine. Watchers are held via `Weak` so a subscriber that is
+/// dropped without unregistering is pruned on the next dispatch rather than
+/// leaking.
+#[derive(Default)]
+pub struct CompactionWatcherRegistry {
+    watchers: Mutex<Vec<(String, Weak<dyn CompactionWatcher>)>>,
+}
+
+impl CompactionWatcherRegistry {
+    /// Subscribe `watcher` under `name`. `name` is passed back to
+    /// `CompactionWatcher::on_compacted` so a watcher registered under
+    /// several names, or sharing logging with others, can tell dispatches
+    /// apart.
+    pub fn register(&self, name: impl Into<String>, watcher: Weak<dyn CompactionWatcher>) {
+        self.watchers.lock().unwrap().push((name.into(), watcher));
+    }
+
+    /// Fans `digest` out to every watcher that is still alive, pruning the
+    /// ones whose `Weak` no longer upgrades.
+    pub fn dispatch(&self, digest: &CompactionDigest) {
+        self.watchers.lock().unwrap().retain(|(name, watcher)| {
+            match watcher.upgrade() {
+                Some(watcher) => {
+                    watcher.on_compacted(name, digest);
+                    true
+                }
+                None => false,
+            }
+        });
+    }
+}
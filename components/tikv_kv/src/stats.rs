@@ -1,6 +1,10 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
-use std::cell::RefCell;
+use std::{
+    cell::RefCell,
+    fmt,
+    time::{Duration, Instant},
+};
 
 use engine_traits::{CF_DEFAULT, CF_LOCK, CF_WRITE, IterMetricsCollector};
 use kvproto::kvrpcpb::{ScanDetail, ScanDetailV2, ScanInfo};
@@ -8,6 +12,72 @@ pub use raftstore::store::{FlowStatistics, FlowStatsReporter};
 
 use super::metrics::{GcKeysCF, GcKeysDetail};
 
+/// Reads jemalloc's per-thread `thread.allocated` mallctl counter so
+/// `StatsCollector` can attribute gross allocation to a single scan. Falls
+/// back to a no-op when a non-jemalloc allocator is configured.
+#[cfg(feature = "jemalloc")]
+mod jemalloc_metrics {
+    use jemalloc_ctl::thread;
+
+    thread_local! {
+        // Cache the resolved `thread.allocatedp` accessor once per OS thread;
+        // resolving it on every read would re-walk the mallctl namespace for
+        // no reason. This is deliberately `thread::allocatedp`, not
+        // `stats::allocated` (the latter is the process-wide total across
+        // every thread, which would make the start/end delta in
+        // `StatsCollector::drop` meaningless for attributing cost to one
+        // scan).
+        static THREAD_ALLOCATEDP: Option<thread::ThreadAllocatedp> =
+            thread::allocatedp::mib().and_then(|m| m.read()).ok();
+    }
+
+    /// Current value of this OS thread's own jemalloc allocation counter, or
+    /// `0` if the stat can't be read.
+    pub fn thread_allocated_bytes() -> u64 {
+        THREAD_ALLOCATEDP.with(|a| a.as_ref().map(|a| a.get()).unwrap_or(0))
+    }
+}
+
+#[cfg(not(feature = "jemalloc"))]
+mod jemalloc_metrics {
+    pub fn thread_allocated_bytes() -> u64 {
+        0
+    }
+}
+
+/// Lets `StatsCollector::drop` fire a `tracing` event for collectors whose op
+/// or tombstone count crossed a configurable threshold, without paying for
+/// the check in builds that don't care about live tracing.
+#[cfg(feature = "tracing-console")]
+mod trace_hook {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// `StatsCollector::drop` emits a trace event once the op count
+    /// accumulated so far in the collector's `CfStatistics`, or the
+    /// tombstones skipped by that one collector, reaches this many.
+    /// `usize::MAX` (the default) disables the hook.
+    pub static SLOW_COLLECTOR_THRESHOLD: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+    pub fn maybe_record_slow_collector(kind: &'static str, op_count: usize, tombstone: usize) {
+        let threshold = SLOW_COLLECTOR_THRESHOLD.load(Ordering::Relaxed);
+        if op_count >= threshold || tombstone >= threshold {
+            tracing::event!(
+                target: "tikv_storage_stats",
+                tracing::Level::WARN,
+                kind,
+                op_count,
+                tombstone,
+                "stats collector exceeded op/tombstone threshold"
+            );
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing-console"))]
+mod trace_hook {
+    pub fn maybe_record_slow_collector(_kind: &'static str, _op_count: usize, _tombstone: usize) {}
+}
+
 const STAT_PROCESSED_KEYS: &str = "processed_keys";
 const STAT_GET: &str = "get";
 const STAT_NEXT: &str = "next";
@@ -21,6 +91,8 @@ const STAT_SEEK_TOMBSTONE: &str = "seek_tombstone";
 const STAT_SEEK_FOR_PREV_TOMBSTONE: &str = "seek_for_prev_tombstone";
 /// Statistics of raw value tombstone by RawKV TTL expired or logical deleted.
 const STAT_RAW_VALUE_TOMBSTONE: &str = "raw_value_tombstone";
+/// Gross bytes allocated, via jemalloc, while a `StatsCollector` was alive.
+const STAT_ALLOCATED_BYTES: &str = "allocated_bytes";
 
 thread_local! {
     pub static RAW_VALUE_TOMBSTONE : RefCell<usize> = const{ RefCell::new(0)};
@@ -41,6 +113,14 @@ pub struct StatsCollector<'a, T: IterMetricsCollector> {
 
     internal_tombstone: usize,
     raw_value_tombstone: usize,
+
+    // Used to attribute gross jemalloc allocation to this collector's lifetime.
+    // The collector must begin and end on the same OS thread: an async executor
+    // that migrates the enclosing task between sampling points would otherwise
+    // mix two threads' counters, so we record the thread identity and skip
+    // accounting rather than report a bogus delta.
+    alloc_thread_id: std::thread::ThreadId,
+    allocated_bytes_start: u64,
 }
 
 impl<'a, T: IterMetricsCollector> StatsCollector<'a, T> {
@@ -52,6 +132,8 @@ impl<'a, T: IterMetricsCollector> StatsCollector<'a, T> {
             kind,
             internal_tombstone,
             raw_value_tombstone: RAW_VALUE_TOMBSTONE.with(|m| *m.borrow()),
+            alloc_thread_id: std::thread::current().id(),
+            allocated_bytes_start: jemalloc_metrics::thread_allocated_bytes(),
         }
     }
 }
@@ -60,26 +142,35 @@ impl<T: IterMetricsCollector> Drop for StatsCollector<'_, T> {
     fn drop(&mut self) {
         self.stats.raw_value_tombstone +=
             RAW_VALUE_TOMBSTONE.with(|m| *m.borrow()) - self.raw_value_tombstone;
+        if std::thread::current().id() == self.alloc_thread_id {
+            let allocated_bytes_end = jemalloc_metrics::thread_allocated_bytes();
+            self.stats.allocated_bytes += allocated_bytes_end.saturating_sub(self.allocated_bytes_start) as usize;
+        }
         let internal_tombstone =
             self.collector.internal_delete_skipped_count() as usize - self.internal_tombstone;
-        match self.kind {
+        let kind = match self.kind {
             StatsKind::Next => {
                 self.stats.next += 1;
                 self.stats.next_tombstone += internal_tombstone;
+                "next"
             }
             StatsKind::Prev => {
                 self.stats.prev += 1;
                 self.stats.prev_tombstone += internal_tombstone;
+                "prev"
             }
             StatsKind::Seek => {
                 self.stats.seek += 1;
                 self.stats.seek_tombstone += internal_tombstone;
+                "seek"
             }
             StatsKind::SeekForPrev => {
                 self.stats.seek_for_prev += 1;
                 self.stats.seek_for_prev_tombstone += internal_tombstone;
+                "seek_for_prev"
             }
-        }
+        };
+        trace_hook::maybe_record_slow_collector(kind, self.stats.total_op_count(), internal_tombstone);
     }
 }
 
@@ -103,9 +194,18 @@ pub struct CfStatistics {
     pub seek_tombstone: usize,
     pub seek_for_prev_tombstone: usize,
     pub raw_value_tombstone: usize,
+    /// Gross bytes allocated, via jemalloc, while a `StatsCollector` backed by
+    /// this `CfStatistics` was alive. Always `0` when built without the
+    /// `jemalloc` feature.
+    pub allocated_bytes: usize,
 }
 
-const STATS_COUNT: usize = 12;
+const STATS_COUNT: usize = 13;
+/// Number of fields `details_enum` reports. One less than [`STATS_COUNT`]:
+/// `allocated_bytes` has no counterpart on `GcKeysDetail` (that enum is
+/// defined in `super::metrics`, which this module doesn't own) and can't be
+/// reported through it until that enum grows a matching variant.
+const STATS_ENUM_COUNT: usize = STATS_COUNT - 1;
 
 impl CfStatistics {
     #[inline]
@@ -127,10 +227,15 @@ impl CfStatistics {
             (STAT_SEEK_TOMBSTONE, self.seek_tombstone),
             (STAT_SEEK_FOR_PREV_TOMBSTONE, self.seek_for_prev_tombstone),
             (STAT_RAW_VALUE_TOMBSTONE, self.raw_value_tombstone),
+            (STAT_ALLOCATED_BYTES, self.allocated_bytes),
         ]
     }
 
-    pub fn details_enum(&self) -> [(GcKeysDetail, usize); STATS_COUNT] {
+    /// Doesn't include `allocated_bytes`: `GcKeysDetail` is defined in
+    /// `super::metrics`, which this series doesn't touch, and has no variant
+    /// for it yet. Use [`Self::details`] (string-keyed) if `allocated_bytes`
+    /// is needed until `GcKeysDetail` is extended there.
+    pub fn details_enum(&self) -> [(GcKeysDetail, usize); STATS_ENUM_COUNT] {
         [
             (GcKeysDetail::processed_keys, self.processed_keys),
             (GcKeysDetail::get, self.get),
@@ -168,6 +273,7 @@ impl CfStatistics {
         self.raw_value_tombstone = self
             .raw_value_tombstone
             .saturating_add(other.raw_value_tombstone);
+        self.allocated_bytes = self.allocated_bytes.saturating_add(other.allocated_bytes);
     }
 
     /// Deprecated
@@ -177,6 +283,58 @@ impl CfStatistics {
         info.set_total(self.total_op_count() as i64);
         info
     }
+
+    /// Emits every field of this CF's statistics as its own `tracing` event
+    /// on the current span, carrying `cf` (e.g. `write`), `field` (the stat
+    /// name from [`Self::details`], e.g. `seek`) and `value`, so a
+    /// tracing-subscriber/console can correlate scan internals with the
+    /// request span they belong to.
+    pub fn record_into_span(&self, cf: &'static str) {
+        for (name, value) in self.details() {
+            tracing::event!(
+                target: "tikv_storage_stats",
+                tracing::Level::TRACE,
+                cf,
+                field = name,
+                value,
+            );
+        }
+    }
+}
+
+/// A callback fired as a long-running scan makes progress. Receives a cheap
+/// `Clone` snapshot of the `Statistics` accumulated so far, letting the
+/// caller observe throughput or implement cooperative cancellation without
+/// waiting for the whole operation to finish.
+pub type ProgressCallback = Box<dyn Fn(&Statistics) + Send>;
+
+/// Tracks when a `Statistics::maybe_report_progress` callback should next
+/// fire: either every `key_stride` processed keys or every `time_interval`,
+/// whichever comes first.
+#[derive(Default)]
+struct ProgressReporter {
+    callback: Option<ProgressCallback>,
+    key_stride: usize,
+    time_interval: Option<Duration>,
+    // Tracked the same way `LoadDataHintStatistics::last_write_over_seek_bound`
+    // tracks its own boundary, so firing the callback never double-counts the
+    // keys already reported.
+    last_reported_processed_keys: usize,
+    last_reported_at: Option<Instant>,
+}
+
+impl fmt::Debug for ProgressReporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressReporter")
+            .field("key_stride", &self.key_stride)
+            .field("time_interval", &self.time_interval)
+            .field(
+                "last_reported_processed_keys",
+                &self.last_reported_processed_keys,
+            )
+            .field("has_callback", &self.callback.is_some())
+            .finish()
+    }
 }
 
 #[derive(Default, Debug)]
@@ -197,6 +355,8 @@ pub struct Statistics {
     // When getting data from default cf, we can check write cf statistics to decide which method
     // should be used to get the data.
     load_data_hint: LoadDataHintStatistics,
+
+    progress: ProgressReporter,
 }
 
 #[derive(Default, Debug)]
@@ -238,7 +398,7 @@ impl Statistics {
         ]
     }
 
-    pub fn details_enum(&self) -> [(GcKeysCF, [(GcKeysDetail, usize); STATS_COUNT]); 3] {
+    pub fn details_enum(&self) -> [(GcKeysCF, [(GcKeysDetail, usize); STATS_ENUM_COUNT]); 3] {
         [
             (GcKeysCF::default, self.data.details_enum()),
             (GcKeysCF::lock, self.lock.details_enum()),
@@ -291,18 +451,175 @@ impl Statistics {
         detail_v2.set_total_versions(self.write.total_op_count() as u64);
         detail_v2.set_processed_versions_size(self.processed_size as u64);
     }
+
+    /// Emits the per-CF statistics as `tracing` events on the current span.
+    /// See [`CfStatistics::record_into_span`].
+    pub fn record_into_span(&self) {
+        self.data.record_into_span(CF_DEFAULT);
+        self.lock.record_into_span(CF_LOCK);
+        self.write.record_into_span(CF_WRITE);
+    }
+
+    /// Total number of get/next/prev/seek/seek_for_prev ops across all CFs.
+    pub fn total_op_count(&self) -> usize {
+        self.lock.total_op_count() + self.write.total_op_count() + self.data.total_op_count()
+    }
+
+    /// Total number of keys visible to the user across all CFs.
+    pub fn total_processed_keys(&self) -> usize {
+        self.lock.processed_keys + self.write.processed_keys + self.data.processed_keys
+    }
+
+    /// A cheap, detached copy of the counters callers are allowed to observe:
+    /// the per-CF statistics and the processed byte count. Used to hand a
+    /// progress callback a snapshot it can keep without aliasing the live
+    /// `Statistics` the scan is still writing into.
+    fn snapshot(&self) -> Statistics {
+        Statistics {
+            lock: self.lock.clone(),
+            write: self.write.clone(),
+            data: self.data.clone(),
+            processed_size: self.processed_size,
+            load_data_hint: LoadDataHintStatistics::default(),
+            progress: ProgressReporter::default(),
+        }
+    }
+
+    /// Registers a progress callback that `maybe_report_progress` will fire
+    /// roughly every `key_stride` processed keys (summed across all CFs) or
+    /// every `time_interval`, whichever comes first. `time_interval` of
+    /// `None` disables the wall-clock trigger.
+    pub fn set_progress_callback(
+        &mut self,
+        key_stride: usize,
+        time_interval: Option<Duration>,
+        callback: ProgressCallback,
+    ) {
+        self.progress = ProgressReporter {
+            callback: Some(callback),
+            key_stride: key_stride.max(1),
+            time_interval,
+            last_reported_processed_keys: self.total_processed_keys(),
+            last_reported_at: Some(Instant::now()),
+        };
+    }
+
+    /// Fires the registered progress callback, if any, once `processed_keys`
+    /// has advanced by at least `key_stride` since it last fired, or
+    /// `time_interval` has elapsed. Must be polled periodically by whoever
+    /// owns this `Statistics` and drives the scan loop incrementing it (a
+    /// `StatsCollector::drop` only ever sees one CF's `CfStatistics`, not the
+    /// aggregate `Statistics` this callback reports on, so it can't poll on
+    /// this `Statistics`'s behalf). Never fires twice for the same
+    /// processed-keys boundary.
+    ///
+    /// Polled once per key by `cdc::observer`'s `get_old_value` callback
+    /// (the one real per-key call site in this tree that already threads a
+    /// `&mut Statistics` through). Any other long-running scan loop that
+    /// owns a `Statistics` should poll this at its own granularity too; a
+    /// missing progress callback (the common case) makes this a cheap no-op.
+    pub fn maybe_report_progress(&mut self) {
+        if self.progress.callback.is_none() {
+            return;
+        }
+        let processed_keys = self.total_processed_keys();
+        let crossed_stride = processed_keys
+            .saturating_sub(self.progress.last_reported_processed_keys)
+            >= self.progress.key_stride;
+        let crossed_interval = match (self.progress.time_interval, self.progress.last_reported_at) {
+            (Some(interval), Some(last)) => last.elapsed() >= interval,
+            _ => false,
+        };
+        if !crossed_stride && !crossed_interval {
+            return;
+        }
+        let snapshot = self.snapshot();
+        (self.progress.callback.as_ref().unwrap())(&snapshot);
+        self.progress.last_reported_processed_keys = processed_keys;
+        self.progress.last_reported_at = Some(Instant::now());
+    }
 }
 
-#[derive(Default, Debug)]
+/// Default smoothing factor for the EWMA rate trackers in
+/// [`StatisticsSummary`]. Larger values weight recent samples more heavily.
+const DEFAULT_RATE_EWMA_ALPHA: f64 = 0.2;
+
+#[derive(Debug)]
 pub struct StatisticsSummary {
     pub stat: Statistics,
     pub count: u64,
+
+    alpha: f64,
+    last_sample: Option<(Instant, usize, usize)>,
+    op_rate: Option<f64>,
+    processed_key_rate: Option<f64>,
+}
+
+impl Default for StatisticsSummary {
+    fn default() -> Self {
+        StatisticsSummary {
+            stat: Statistics::default(),
+            count: 0,
+            alpha: DEFAULT_RATE_EWMA_ALPHA,
+            last_sample: None,
+            op_rate: None,
+            processed_key_rate: None,
+        }
+    }
 }
 
 impl StatisticsSummary {
+    /// Builds a summary whose EWMA rate trackers use the given smoothing
+    /// factor instead of [`DEFAULT_RATE_EWMA_ALPHA`].
+    pub fn with_alpha(alpha: f64) -> Self {
+        StatisticsSummary {
+            alpha,
+            ..Default::default()
+        }
+    }
+
     pub fn add_statistics(&mut self, v: &Statistics) {
         self.stat.add(v);
         self.count += 1;
+        self.update_rates();
+    }
+
+    fn update_rates(&mut self) {
+        let now = Instant::now();
+        let total_ops = self.stat.total_op_count();
+        let processed_keys = self.stat.total_processed_keys();
+        if let Some((last_time, last_ops, last_keys)) = self.last_sample {
+            let delta_seconds = now.saturating_duration_since(last_time).as_secs_f64();
+            if delta_seconds <= 0.0 {
+                // Two samples landed in the same instant; skip rather than
+                // divide by zero.
+                return;
+            }
+            let instantaneous_op_rate = (total_ops - last_ops) as f64 / delta_seconds;
+            let instantaneous_key_rate = (processed_keys - last_keys) as f64 / delta_seconds;
+            self.op_rate = Some(match self.op_rate {
+                // First instantaneous sample: seed the smoothed value instead
+                // of blending against zero.
+                None => instantaneous_op_rate,
+                Some(prev) => self.alpha * instantaneous_op_rate + (1.0 - self.alpha) * prev,
+            });
+            self.processed_key_rate = Some(match self.processed_key_rate {
+                None => instantaneous_key_rate,
+                Some(prev) => self.alpha * instantaneous_key_rate + (1.0 - self.alpha) * prev,
+            });
+        }
+        self.last_sample = Some((now, total_ops, processed_keys));
+    }
+
+    /// Smoothed ops/sec, combining get/next/prev/seek/seek_for_prev across all
+    /// CFs.
+    pub fn op_rate(&self) -> f64 {
+        self.op_rate.unwrap_or(0.0)
+    }
+
+    /// Smoothed processed-keys/sec across all CFs.
+    pub fn processed_key_rate(&self) -> f64 {
+        self.processed_key_rate.unwrap_or(0.0)
     }
 }
 
@@ -325,3 +642,18 @@ pub struct StageLatencyStats {
     pub wait_wall_time_ns: u64,
     pub process_wall_time_ns: u64,
 }
+
+impl StageLatencyStats {
+    /// Emits each stage latency as a `tracing` event on the current span so
+    /// it can be correlated with the request span it measures.
+    pub fn record_into_span(&self) {
+        tracing::event!(
+            target: "tikv_storage_stats",
+            tracing::Level::TRACE,
+            schedule_wait_time_ns = self.schedule_wait_time_ns,
+            snapshot_wait_time_ns = self.snapshot_wait_time_ns,
+            wait_wall_time_ns = self.wait_wall_time_ns,
+            process_wall_time_ns = self.process_wall_time_ns,
+        );
+    }
+}
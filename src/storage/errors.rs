@@ -2,11 +2,16 @@
 
 //! Types for storage related errors and associated helper methods.
 use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
     convert::TryFrom,
     error::Error as StdError,
     fmt::{self, Debug, Display, Formatter},
     io::Error as IoError,
-    sync::Arc,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
 };
 
 use error_code::{self, ErrorCode, ErrorCodeExt};
@@ -46,9 +51,26 @@ pub enum ErrorInner {
     #[error("{0}")]
     Io(#[from] IoError),
 
+    #[error("{component:?} is busy: {reason}")]
+    ComponentBusy {
+        component: BusyComponent,
+        reason: String,
+        retry_after_ms: u64,
+        queue_depth: Option<u64>,
+    },
+
+    /// Deprecated: superseded by `ComponentBusy { component: BusyComponent::Scheduler, .. }`,
+    /// which carries the same information plus a configurable backoff and
+    /// queue depth. Kept around (unconstructed by this module) only so code
+    /// elsewhere in the tree that still matches on this variant keeps
+    /// compiling; new callers should use [`ErrorInner::component_busy`].
+    #[deprecated(note = "use ErrorInner::component_busy(BusyComponent::Scheduler, ..) instead")]
     #[error("scheduler is too busy")]
     SchedTooBusy,
 
+    /// Deprecated: see [`ErrorInner::SchedTooBusy`]; superseded by
+    /// `ComponentBusy { component: BusyComponent::GcWorker, .. }`.
+    #[deprecated(note = "use ErrorInner::component_busy(BusyComponent::GcWorker, ..) instead")]
     #[error("gc worker is too busy")]
     GcWorkerTooBusy,
 
@@ -115,6 +137,40 @@ impl ErrorInner {
             ),
         }
     }
+
+    /// Builds a [`ErrorInner::ComponentBusy`] for `component`, using its
+    /// default reason text and backoff. Pass `queue_depth` when the caller
+    /// has a live queue-length figure to report; it's surfaced through to
+    /// `ServerIsBusy` so clients see an actionable wait estimate instead of
+    /// a fixed string.
+    pub fn component_busy(component: BusyComponent, queue_depth: Option<u64>) -> Self {
+        ErrorInner::ComponentBusy {
+            reason: component.default_reason().to_owned(),
+            retry_after_ms: COMPONENT_BUSY_BACKOFF_MS,
+            component,
+            queue_depth,
+        }
+    }
+}
+
+/// Background component that can report itself overloaded via
+/// [`ErrorInner::ComponentBusy`]. New overload sources should be added here
+/// rather than as new top-level `ErrorInner` variants, so callers get
+/// uniform retry/backpressure handling instead of reimplementing it per
+/// component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusyComponent {
+    Scheduler,
+    GcWorker,
+}
+
+impl BusyComponent {
+    fn default_reason(&self) -> &'static str {
+        match self {
+            BusyComponent::Scheduler => SCHEDULER_IS_BUSY,
+            BusyComponent::GcWorker => GC_WORKER_IS_BUSY,
+        }
+    }
 }
 
 impl From<DeadlineError> for ErrorInner {
@@ -131,19 +187,301 @@ pub struct Error(#[from] pub Box<ErrorInner>);
 impl From<ErrorInner> for Error {
     #[inline]
     fn from(e: ErrorInner) -> Self {
-        Error(Box::new(e))
+        let _scope = TraceScope::enter();
+        push_trace_frame("storage::errors::ErrorInner", e.to_string());
+        push_source_chain_frames(StdError::source(&e));
+        let err = Error(Box::new(e));
+        TraceScope::finish(&err);
+        err
     }
 }
 
-impl<T: Into<ErrorInner>> From<T> for Error {
+impl<T: Into<ErrorInner> + StdError> From<T> for Error {
     #[inline]
     default fn from(err: T) -> Self {
-        let err = err.into();
-        err.into()
+        let _scope = TraceScope::enter();
+        // Push `err`'s own frame, plus one per hop of its `source()` chain,
+        // *before* converting it into `ErrorInner` below — once it's an
+        // `ErrorInner` it's built directly into `Error` rather than routed
+        // through `impl From<ErrorInner> for Error`, so that impl's own
+        // frame push doesn't fire a second time for the same conversion.
+        push_trace_frame(std::any::type_name::<T>(), err.to_string());
+        push_source_chain_frames(err.source());
+        let inner: ErrorInner = err.into();
+        let err = Error(Box::new(inner));
+        TraceScope::finish(&err);
+        err
+    }
+}
+
+/// Walks the causal chain beneath a conversion's top-level error (already
+/// pushed by the caller), so e.g. a `txn::Error` that wraps an `mvcc::Error`
+/// that wraps a `kv::Error` gets a frame per hop instead of only the top one
+/// being recorded and the path through `mvcc`/`kv` being lost. Each hop here
+/// is a type-erased `dyn StdError`, so unlike the top frame (whose module is
+/// known statically from the caller's `T`) these can't carry a real module
+/// path — they're labeled generically, but still carry that hop's own
+/// message, which is what actually distinguishes one hop from the next.
+fn push_source_chain_frames(mut source: Option<&(dyn StdError + 'static)>) {
+    while let Some(s) = source {
+        push_trace_frame("<source>", s.to_string());
+        source = s.source();
+    }
+}
+
+/// Bounds how many causal frames [`ErrorTrace`] keeps. An error that bounces
+/// through this many `From` conversions on its way up almost certainly has a
+/// cyclic or runaway conversion chain, not a legitimately deep one.
+const MAX_TRACE_FRAMES: usize = 32;
+
+/// Bounds how many in-flight `Error`s' traces [`error_trace_registry`]
+/// remembers. `Error` can't carry its trace directly (it's destructured via
+/// `box` patterns all over this module, and Rust forbids a partial move out
+/// of a type with a custom `Drop`, which rules out cleaning up on
+/// `Error::drop`). So entries are keyed by the `Error`'s heap address
+/// instead and evicted oldest-first once the registry is full, trading an
+/// occasional lost trace (on a very long-lived, never-read `Error` evicted
+/// before anyone calls `trace()`) for never attaching the wrong one.
+const MAX_TRACKED_ERROR_TRACES: usize = 4096;
+
+thread_local! {
+    // Accumulates the frames for the `Error` currently under construction on
+    // this thread. Unlike the registry below, this is genuinely scoped to a
+    // single, possibly-nested, conversion chain: `TraceScope` resets it when
+    // a chain starts and `TraceScope::finish` reads it back out and files it
+    // away under the finished `Error`'s address before the chain's depth
+    // reaches zero, so it never leaks into an unrelated error built
+    // afterwards.
+    static CURRENT_ERROR_TRACE: RefCell<ErrorTrace> = RefCell::new(ErrorTrace::default());
+    // Depth of nested `From` conversions currently building an `Error` on
+    // this thread. Only the outermost one should
+    // reset `CURRENT_ERROR_TRACE`, and only the outermost one's `finish`
+    // call should file the assembled trace away.
+    static TRACE_DEPTH: RefCell<u32> = const { RefCell::new(0) };
+}
+
+/// RAII guard marking one `From` conversion's place in the nesting depth,
+/// so only the outermost conversion in a chain resets the trace and files it
+/// away.
+struct TraceScope;
+
+impl TraceScope {
+    fn enter() -> Self {
+        let is_outermost = TRACE_DEPTH.with(|d| {
+            let mut d = d.borrow_mut();
+            let was_zero = *d == 0;
+            *d += 1;
+            was_zero
+        });
+        if is_outermost {
+            CURRENT_ERROR_TRACE.with(|t| *t.borrow_mut() = ErrorTrace::default());
+        }
+        TraceScope
+    }
+
+    /// Called by a `From` conversion right after it builds `err`, before its
+    /// own `TraceScope` guard drops. Files the accumulated trace away under
+    /// `err`'s address, but only when this is the outermost conversion in
+    /// the chain (depth is still 1, i.e. every nested conversion below this
+    /// one has already finished and dropped its own guard) — otherwise the
+    /// trace isn't complete yet and the outer frame that called into this
+    /// one will file it away itself once it, in turn, finishes.
+    fn finish(err: &Error) {
+        let is_outermost = TRACE_DEPTH.with(|d| *d.borrow() == 1);
+        if is_outermost {
+            let trace = CURRENT_ERROR_TRACE.with(|t| t.borrow().clone());
+            error_trace_registry()
+                .lock()
+                .unwrap()
+                .insert(error_trace_key(err), trace);
+        }
+    }
+}
+
+impl Drop for TraceScope {
+    fn drop(&mut self) {
+        TRACE_DEPTH.with(|d| *d.borrow_mut() -= 1);
+    }
+}
+
+fn push_trace_frame(module: &'static str, message: String) {
+    CURRENT_ERROR_TRACE.with(|t| {
+        let mut t = t.borrow_mut();
+        if t.frames.len() >= MAX_TRACE_FRAMES {
+            return;
+        }
+        t.frames.push(ErrorTraceFrame { module, message });
+        #[cfg(feature = "capture-backtrace")]
+        if t.backtrace.is_none() {
+            t.backtrace = Some(std::backtrace::Backtrace::force_capture());
+        }
+    });
+}
+
+/// A single frame in an [`Error`]'s causal chain, recorded at the point it
+/// crossed into `storage::errors` via a `From` conversion.
+#[derive(Debug, Clone)]
+pub struct ErrorTraceFrame {
+    pub module: &'static str,
+    pub message: String,
+}
+
+/// Ordered causal chain leading to one specific [`Error`], plus an optional
+/// backtrace captured at the same time. Backtrace capture is gated behind
+/// the `capture-backtrace` feature so production builds don't pay for it.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorTrace {
+    pub frames: Vec<ErrorTraceFrame>,
+    #[cfg(feature = "capture-backtrace")]
+    pub backtrace: Option<std::backtrace::Backtrace>,
+}
+
+/// Process-wide table of completed `Error`s' traces, keyed by the `Error`'s
+/// heap address. This is race-free despite being keyed by a raw address: the
+/// only way to look a key up is through a live `&Error` (see
+/// [`Error::trace`]), and for as long as that reference is alive its
+/// allocation can't have been freed and reused by some other `Error`, so the
+/// address can only ever resolve to the trace of the `Error` it actually
+/// came from.
+struct ErrorTraceRegistry {
+    traces: HashMap<usize, ErrorTrace>,
+    order: VecDeque<usize>,
+}
+
+impl ErrorTraceRegistry {
+    fn insert(&mut self, key: usize, trace: ErrorTrace) {
+        if !self.traces.contains_key(&key) {
+            self.order.push_back(key);
+        }
+        self.traces.insert(key, trace);
+        while self.order.len() > MAX_TRACKED_ERROR_TRACES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.traces.remove(&oldest);
+            }
+        }
+    }
+}
+
+fn error_trace_registry() -> &'static Mutex<ErrorTraceRegistry> {
+    static REGISTRY: OnceLock<Mutex<ErrorTraceRegistry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        Mutex::new(ErrorTraceRegistry {
+            traces: HashMap::default(),
+            order: VecDeque::default(),
+        })
+    })
+}
+
+fn error_trace_key(err: &Error) -> usize {
+    std::ptr::addr_of!(*err.0) as usize
+}
+
+impl Error {
+    /// Returns the causal chain captured while this specific error
+    /// propagated through this module's `From` conversions, independent of
+    /// whatever else has been constructed on this or any other thread since.
+    /// Returns an empty trace if this `Error`'s entry has been evicted from
+    /// [`error_trace_registry`] — see [`MAX_TRACKED_ERROR_TRACES`].
+    pub fn trace(&self) -> ErrorTrace {
+        error_trace_registry()
+            .lock()
+            .unwrap()
+            .traces
+            .get(&error_trace_key(self))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// Default backoff suggested for a single lock/write conflict before the
+/// caller retries. Deliberately small: these conflicts usually clear within
+/// one or two other transactions' lifetimes.
+const LOCK_CONFLICT_BACKOFF_MS: u64 = 100;
+/// Default backoff suggested when a background component (scheduler, GC
+/// worker) reports it is overloaded. Larger than the lock-conflict backoff
+/// because the condition tends to persist for longer.
+const COMPONENT_BUSY_BACKOFF_MS: u64 = 500;
+
+/// Machine-readable classification of how a storage [`Error`] should be
+/// retried, so callers don't have to infer it from a `Debug`-formatted
+/// string the way `KeyError::retryable` has historically required.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryClass {
+    /// Retrying will not help; the caller should surface the error.
+    NonRetryable,
+    /// Safe to retry right away, with no backoff.
+    RetryImmediate,
+    /// Retry after roughly `suggested_ms`, e.g. a lock/write conflict or an
+    /// overloaded component that is expected to clear.
+    RetryWithBackoff { suggested_ms: u64 },
+    /// The request was routed to the wrong region (leader moved, epoch
+    /// changed); the caller should refresh its region cache before retrying.
+    RetryAfterRegionRefresh,
+}
+
+impl Error {
+    /// Classifies this error for retry purposes. Mirrors the fail-fast /
+    /// retry-budget model other KV systems expose to clients, so a caller
+    /// can implement uniform exponential backoff instead of pattern-matching
+    /// on error variants or parsing `Debug` strings.
+    #[allow(deprecated)]
+    pub fn retry_class(&self) -> RetryClass {
+        match self {
+            Error(box ErrorInner::Txn(TxnError(box TxnErrorInner::Mvcc(MvccError(
+                box MvccErrorInner::KeyIsLocked(_),
+            )))))
+            | Error(box ErrorInner::Txn(TxnError(box TxnErrorInner::Engine(KvError(
+                box KvErrorInner::KeyIsLocked(_),
+            )))))
+            | Error(box ErrorInner::Kv(KvError(box KvErrorInner::KeyIsLocked(_))))
+            | Error(box ErrorInner::Txn(TxnError(box TxnErrorInner::Mvcc(MvccError(
+                box MvccErrorInner::WriteConflict { .. },
+            )))))
+            | Error(box ErrorInner::Txn(TxnError(box TxnErrorInner::Mvcc(MvccError(
+                box MvccErrorInner::Deadlock { .. },
+            ))))) => RetryClass::RetryWithBackoff {
+                suggested_ms: LOCK_CONFLICT_BACKOFF_MS,
+            },
+            Error(box ErrorInner::ComponentBusy { retry_after_ms, .. }) => {
+                RetryClass::RetryWithBackoff {
+                    suggested_ms: *retry_after_ms,
+                }
+            }
+            Error(box ErrorInner::SchedTooBusy) | Error(box ErrorInner::GcWorkerTooBusy) => {
+                RetryClass::RetryWithBackoff {
+                    suggested_ms: COMPONENT_BUSY_BACKOFF_MS,
+                }
+            }
+            Error(box ErrorInner::KeyTooLarge { .. })
+            | Error(box ErrorInner::InvalidCf(_))
+            | Error(box ErrorInner::CfDeprecated(_))
+            | Error(box ErrorInner::TtlLenNotEqualsToPairs)
+            | Error(box ErrorInner::ApiVersionNotMatched { .. })
+            | Error(box ErrorInner::InvalidKeyMode { .. })
+            | Error(box ErrorInner::InvalidKeyRangeMode { .. })
+            | Error(box ErrorInner::Txn(TxnError(box TxnErrorInner::Mvcc(MvccError(
+                box MvccErrorInner::AssertionFailed { .. },
+            ))))) => RetryClass::NonRetryable,
+            _ => match extract_region_error_from_error(self) {
+                Some(region_err)
+                    if region_err.has_not_leader()
+                        || region_err.has_epoch_not_match()
+                        || region_err.has_region_not_found()
+                        || region_err.has_stale_command() =>
+                {
+                    RetryClass::RetryAfterRegionRefresh
+                }
+                Some(region_err) if region_err.has_server_is_busy() => RetryClass::RetryWithBackoff {
+                    suggested_ms: COMPONENT_BUSY_BACKOFF_MS,
+                },
+                _ => RetryClass::NonRetryable,
+            },
+        }
     }
 }
 
 impl ErrorCodeExt for Error {
+    #[allow(deprecated)]
     fn error_code(&self) -> ErrorCode {
         match self.0.as_ref() {
             ErrorInner::Kv(e) => e.error_code(),
@@ -152,6 +490,10 @@ impl ErrorCodeExt for Error {
             ErrorInner::Closed => error_code::storage::CLOSED,
             ErrorInner::Other(_) => error_code::storage::UNKNOWN,
             ErrorInner::Io(_) => error_code::storage::IO,
+            ErrorInner::ComponentBusy { component, .. } => match component {
+                BusyComponent::Scheduler => error_code::storage::SCHED_TOO_BUSY,
+                BusyComponent::GcWorker => error_code::storage::GC_WORKER_TOO_BUSY,
+            },
             ErrorInner::SchedTooBusy => error_code::storage::SCHED_TOO_BUSY,
             ErrorInner::GcWorkerTooBusy => error_code::storage::GC_WORKER_TOO_BUSY,
             ErrorInner::KeyTooLarge { .. } => error_code::storage::KEY_TOO_LARGE,
@@ -270,6 +612,7 @@ pub fn get_tag_from_header(header: &errorpb::Error) -> &'static str {
     get_error_kind_from_header(header).get_str()
 }
 
+#[allow(deprecated)]
 pub fn extract_region_error_from_error(e: &Error) -> Option<errorpb::Error> {
     match e {
         // TODO: use `Error::cause` instead.
@@ -318,17 +661,39 @@ pub fn extract_region_error_from_error(e: &Error) -> Option<errorpb::Error> {
             err.set_message(invalid_max_ts_update.to_string());
             Some(err)
         }
+        Error(box ErrorInner::ComponentBusy {
+            reason,
+            retry_after_ms,
+            queue_depth,
+            ..
+        }) => {
+            let mut err = errorpb::Error::default();
+            let mut server_is_busy_err = errorpb::ServerIsBusy::default();
+            // `backoff_ms` is the structured field clients are meant to read
+            // for how long to wait before retrying; `reason` stays free text
+            // (there's no structured slot on `ServerIsBusy` for queue depth,
+            // so that still only shows up there).
+            server_is_busy_err.set_reason(match queue_depth {
+                Some(depth) => format!("{}, queue depth {}", reason, depth),
+                None => reason.clone(),
+            });
+            server_is_busy_err.set_backoff_ms(*retry_after_ms);
+            err.set_server_is_busy(server_is_busy_err);
+            Some(err)
+        }
         Error(box ErrorInner::SchedTooBusy) => {
             let mut err = errorpb::Error::default();
             let mut server_is_busy_err = errorpb::ServerIsBusy::default();
-            server_is_busy_err.set_reason(SCHEDULER_IS_BUSY.to_owned());
+            server_is_busy_err.set_reason(SCHEDULER_IS_BUSY.to_string());
+            server_is_busy_err.set_backoff_ms(COMPONENT_BUSY_BACKOFF_MS);
             err.set_server_is_busy(server_is_busy_err);
             Some(err)
         }
         Error(box ErrorInner::GcWorkerTooBusy) => {
             let mut err = errorpb::Error::default();
             let mut server_is_busy_err = errorpb::ServerIsBusy::default();
-            server_is_busy_err.set_reason(GC_WORKER_IS_BUSY.to_owned());
+            server_is_busy_err.set_reason(GC_WORKER_IS_BUSY.to_string());
+            server_is_busy_err.set_backoff_ms(COMPONENT_BUSY_BACKOFF_MS);
             err.set_server_is_busy(server_is_busy_err);
             Some(err)
         }
@@ -376,23 +741,140 @@ fn get_or_insert_default_for_key_error_debug_info(
     }
 }
 
+thread_local! {
+    // Off by default: collecting and serializing `MvccInfo` for every
+    // retryable error is not free, and most callers never read
+    // `DebugInfo.mvcc_info`. The RPC layer enters [`MvccDebugInfoScope`] for
+    // the duration of a request based on a per-request context flag, and
+    // `add_debug_mvcc_for_key_error` checks this flag rather than always
+    // attaching whatever `MvccInfo` happens to be available.
+    static COLLECT_MVCC_DEBUG_INFO: Cell<bool> = const { Cell::new(false) };
+}
+
+/// RAII guard enabling MVCC debug-info collection (see
+/// [`add_debug_mvcc_for_key_error`]) for the scope of one request, restoring
+/// the previous setting on drop so nested calls don't clobber an outer
+/// scope's choice. The RPC layer should enter this based on the request's
+/// `Context` before dispatching into storage.
+pub struct MvccDebugInfoScope {
+    previous: bool,
+}
+
+impl MvccDebugInfoScope {
+    pub fn enter(enabled: bool) -> Self {
+        let previous = COLLECT_MVCC_DEBUG_INFO.with(|c| c.replace(enabled));
+        MvccDebugInfoScope { previous }
+    }
+}
+
+impl Drop for MvccDebugInfoScope {
+    fn drop(&mut self) {
+        COLLECT_MVCC_DEBUG_INFO.with(|c| c.set(self.previous));
+    }
+}
+
+fn mvcc_debug_info_collection_enabled() -> bool {
+    COLLECT_MVCC_DEBUG_INFO.with(|c| c.get())
+}
+
+/// Default byte budget for the `MvccInfo` attached to one `MvccDebugInfo`.
+/// The budget is a process-wide value changed only through
+/// [`set_mvcc_debug_info_byte_budget`]; no server config entry feeds it.
+const DEFAULT_MVCC_DEBUG_INFO_BYTE_BUDGET: usize = 64 * 1024;
+
+/// Current byte budget, updatable at runtime via
+/// [`set_mvcc_debug_info_byte_budget`] the same way other online-updatable
+/// server configs are threaded into this crate through an atomic cell.
+static MVCC_DEBUG_INFO_BYTE_BUDGET: AtomicUsize =
+    AtomicUsize::new(DEFAULT_MVCC_DEBUG_INFO_BYTE_BUDGET);
+
+/// Updates the byte budget used by [`add_debug_mvcc_for_key_error`] for all
+/// subsequent calls on any thread.
+pub fn set_mvcc_debug_info_byte_budget(bytes: usize) {
+    MVCC_DEBUG_INFO_BYTE_BUDGET.store(bytes, Ordering::Relaxed);
+}
+
+/// Rough, deliberately approximate per-write-record byte cost used to
+/// budget how many `writes` entries get serialized. A byte-accurate count
+/// would require actually encoding each record, which defeats the point of
+/// a cheap budget check on a path that's already building an error.
+const APPROX_WRITE_RECORD_BYTES: usize = 64;
+
+/// Keeps only the newest (by `commit_ts`) writes that fit in `byte_budget`,
+/// but always at least one, sorting `writes` newest-first in place. Returns
+/// whether any entries were elided.
+fn truncate_writes_to_budget(
+    writes: &mut Vec<(TimeStamp, txn_types::Write)>,
+    byte_budget: usize,
+) -> bool {
+    writes.sort_by(|a, b| b.0.cmp(&a.0));
+    let budget_entries = (byte_budget / APPROX_WRITE_RECORD_BYTES).max(1);
+    if writes.len() > budget_entries {
+        writes.truncate(budget_entries);
+        true
+    } else {
+        false
+    }
+}
+
+/// Centralizes MVCC debug-info attachment: the request-gating check, the
+/// byte-budgeted truncation, the proto conversion, and the default-CF
+/// value stripping all live here so every `MvccErrorInner` variant that
+/// can supply an `MvccInfo` goes through one path instead of duplicating
+/// this logic per variant.
+///
+/// Only `TxnLockNotFound` and `CommitTsExpired` call this, as the two
+/// `MvccErrorInner` variants that carry an `mvcc_info`. Other key errors
+/// (`WriteConflict`, `KeyIsLocked`, `AlreadyExist`, `Deadlock`,
+/// `PessimisticLockNotFound`) get no MVCC debug info.
 fn add_debug_mvcc_for_key_error(
     err: &mut kvrpcpb::KeyError,
     key: &[u8],
     mvcc_info: Option<types::MvccInfo>,
 ) {
+    if !mvcc_debug_info_collection_enabled() {
+        return;
+    }
     if let Some(mut mvcc) = mvcc_info {
-        let debug_info = get_or_insert_default_for_key_error_debug_info(err);
         // remove the values in default CF to reduce the size of the response.
         mvcc.values.clear();
+        let total_writes = mvcc.writes.len() as u64;
+        let byte_budget = MVCC_DEBUG_INFO_BYTE_BUDGET.load(Ordering::Relaxed);
+        let truncated = truncate_writes_to_budget(&mut mvcc.writes, byte_budget);
+
+        let debug_info = get_or_insert_default_for_key_error_debug_info(err);
         // set mvcc info to debug_info
         let mut mvcc_debug_info = kvrpcpb::MvccDebugInfo::default();
         mvcc_debug_info.set_key(key.to_owned());
         mvcc_debug_info.set_mvcc(mvcc.into_proto());
+        mvcc_debug_info.set_total_writes(total_writes);
+        mvcc_debug_info.set_truncated(truncated);
         debug_info.mvcc_info.push(mvcc_debug_info);
     }
 }
 
+/// Serializes the causal chain and (if captured) backtrace from `trace` into
+/// `err`'s `DebugInfo`, so a client debugging a cross-region anomaly sees the
+/// full `txn` -> `mvcc` -> `kv` path instead of just the leaf variant's
+/// `{:?}` dump.
+fn add_error_trace_for_key_error(err: &mut kvrpcpb::KeyError, trace: &ErrorTrace) {
+    if trace.frames.is_empty() {
+        return;
+    }
+    let debug_info = get_or_insert_default_for_key_error_debug_info(err);
+    debug_info.set_trace_frames(
+        trace
+            .frames
+            .iter()
+            .map(|f| format!("{}: {}", f.module, f.message))
+            .collect(),
+    );
+    #[cfg(feature = "capture-backtrace")]
+    if let Some(backtrace) = &trace.backtrace {
+        debug_info.set_backtrace(backtrace.to_string());
+    }
+}
+
 pub fn extract_key_error(err: &Error) -> kvrpcpb::KeyError {
     let mut key_error = kvrpcpb::KeyError::default();
     match err {
@@ -459,6 +941,7 @@ pub fn extract_key_error(err: &Error) -> kvrpcpb::KeyError {
             txn_lock_not_found.set_key(key.clone());
             key_error.set_txn_lock_not_found(txn_lock_not_found);
             add_debug_mvcc_for_key_error(&mut key_error, key, mvcc_info.clone());
+            add_error_trace_for_key_error(&mut key_error, &err.trace());
         }
         Error(box ErrorInner::Txn(TxnError(box TxnErrorInner::Mvcc(MvccError(
             box MvccErrorInner::TxnNotFound { start_ts, key },
@@ -501,6 +984,7 @@ pub fn extract_key_error(err: &Error) -> kvrpcpb::KeyError {
             commit_ts_expired.set_min_commit_ts(min_commit_ts.into_inner());
             key_error.set_commit_ts_expired(commit_ts_expired);
             add_debug_mvcc_for_key_error(&mut key_error, key, mvcc_info.clone());
+            add_error_trace_for_key_error(&mut key_error, &err.trace());
         }
         Error(box ErrorInner::Txn(TxnError(box TxnErrorInner::Mvcc(MvccError(
             box MvccErrorInner::CommitTsTooLarge { min_commit_ts, .. },
@@ -536,6 +1020,14 @@ pub fn extract_key_error(err: &Error) -> kvrpcpb::KeyError {
         _ => {
             error!(?*err; "txn aborts");
             key_error.set_abort(format!("{:?}", err));
+            add_error_trace_for_key_error(&mut key_error, &err.trace());
+        }
+    }
+    // Give the client a retry hint it doesn't have to derive by parsing
+    // `retryable`/`abort`, for variants above that didn't already set one.
+    if key_error.get_retryable().is_empty() {
+        if let RetryClass::RetryWithBackoff { suggested_ms } = err.retry_class() {
+            key_error.set_retryable(format!("retry with backoff, suggested_ms={}", suggested_ms));
         }
     }
     key_error
@@ -583,6 +1075,54 @@ pub fn extract_key_errors(res: Result<Vec<Result<()>>>) -> Vec<kvrpcpb::KeyError
     }
 }
 
+/// Batch-aware counterpart to [`extract_kv_pairs`]. When the whole batch's
+/// `Result` is `Err` (e.g. the deadline expired or the scheduler was too
+/// busy before any individual key was attempted), attribute the shared
+/// failure to every one of `keys` instead of collapsing the batch into a
+/// single synthetic `KvPair`, so callers can still correlate failures back
+/// to individual keys the way [`map_kv_pairs`] already does for per-key
+/// errors.
+pub fn extract_kv_pairs_for_keys(
+    res: Result<Vec<Result<KvPair>>>,
+    keys: &[Vec<u8>],
+) -> Vec<kvrpcpb::KvPair> {
+    match res {
+        Ok(res) => map_kv_pairs(res),
+        Err(e) => {
+            let batch_err = BatchError::from(e);
+            keys.iter()
+                .map(|key| {
+                    let mut pair = kvrpcpb::KvPair::default();
+                    pair.set_key(key.clone());
+                    pair.set_error(batch_err.for_key(key));
+                    pair
+                })
+                .collect()
+        }
+    }
+}
+
+/// Batch-aware counterpart to [`extract_key_errors`]; see
+/// [`extract_kv_pairs_for_keys`] for when the attribution happens.
+pub fn extract_key_errors_for_keys(
+    res: Result<Vec<Result<()>>>,
+    keys: &[Vec<u8>],
+) -> Vec<kvrpcpb::KeyError> {
+    match res {
+        Ok(res) => res
+            .into_iter()
+            .filter_map(|x| match x {
+                Err(e) => Some(extract_key_error(&e)),
+                Ok(_) => None,
+            })
+            .collect(),
+        Err(e) => {
+            let batch_err = BatchError::from(e);
+            keys.iter().map(|key| batch_err.for_key(key)).collect()
+        }
+    }
+}
+
 /// The shared version of [`Error`]. In some cases, it's necessary to pass a
 /// single error to more than one requests, since the inner error doesn't
 /// support cloning.
@@ -590,6 +1130,58 @@ pub fn extract_key_errors(res: Result<Vec<Result<()>>>) -> Vec<kvrpcpb::KeyError
 #[error(transparent)]
 pub struct SharedError(pub Arc<Error>);
 
+/// A single (non-cloneable) `Error` that caused a batch request to fail as a
+/// whole, shared out across every key (or key range) in the batch. Built on
+/// top of [`SharedError`] so the underlying error is stored once rather than
+/// reformatted or duplicated per key.
+#[derive(Debug, Clone)]
+pub struct BatchError(SharedError);
+
+impl From<Error> for BatchError {
+    fn from(err: Error) -> Self {
+        BatchError(SharedError::from(err))
+    }
+}
+
+impl BatchError {
+    /// Builds the `KeyError` this batch failure implies for `key`.
+    ///
+    /// Only sets `abort` when `extract_key_error` didn't already populate a
+    /// structured field (`locked`, `conflict`, ...): those variants already
+    /// tell the client exactly what happened, and stacking `abort` on top
+    /// would hand back two conflicting signals in the same `KeyError`. The
+    /// catch-all variants `extract_key_error` itself falls back to `abort`
+    /// for are the only ones that get the batch-context `abort` message
+    /// here too.
+    pub fn for_key(&self, key: &[u8]) -> kvrpcpb::KeyError {
+        let mut key_error = extract_key_error(&self.0.0);
+        if !has_structured_field(&key_error) {
+            key_error.set_abort(format!(
+                "batch failed for key {}: {:?}",
+                log_wrappers::hex_encode_upper(key),
+                self.0.0
+            ));
+        }
+        key_error
+    }
+}
+
+/// Whether `key_error` already carries one of `KeyError`'s structured
+/// outcome fields, as opposed to only the free-text `abort`/`retryable`
+/// fields `extract_key_error`'s catch-all arm sets.
+fn has_structured_field(key_error: &kvrpcpb::KeyError) -> bool {
+    key_error.has_locked()
+        || key_error.has_conflict()
+        || key_error.has_already_exist()
+        || key_error.has_txn_lock_not_found()
+        || key_error.has_txn_not_found()
+        || key_error.has_deadlock()
+        || key_error.has_commit_ts_expired()
+        || key_error.has_commit_ts_too_large()
+        || key_error.has_assertion_failed()
+        || key_error.has_primary_mismatch()
+}
+
 impl SharedError {
     pub fn inner(&self) -> &ErrorInner {
         &self.0.0
@@ -679,7 +1271,23 @@ mod test {
         }
     }
 
+    // `extract_key_error` now also attaches the error's own `ErrorTrace`
+    // (see `add_error_trace_for_key_error` and `Error::trace`), which these tests
+    // don't otherwise care about. Strip it so the assertions below stay
+    // focused on the mvcc debug info they're actually testing.
+    fn strip_trace(mut got: kvrpcpb::KeyError) -> kvrpcpb::KeyError {
+        if let Some(debug_info) = got.debug_info.as_mut() {
+            debug_info.clear_trace_frames();
+            debug_info.clear_backtrace();
+            if debug_info.mvcc_info.is_empty() {
+                got.clear_debug_info();
+            }
+        }
+        got
+    }
+
     fn expected_debug_info_from_mvcc(key: Vec<u8>, mvcc: MvccInfo) -> kvrpcpb::DebugInfo {
+        let total_writes = mvcc.writes.len() as u64;
         let mut expect_pb_mvcc_info = mvcc.clone().into_proto();
         // should clear the values in default CF to reduce the size of the response.
         expect_pb_mvcc_info.values.clear();
@@ -687,6 +1295,8 @@ mod test {
             mvcc_info: vec![kvrpcpb::MvccDebugInfo {
                 key,
                 mvcc: Some(expect_pb_mvcc_info).into(),
+                total_writes,
+                truncated: false,
                 ..Default::default()
             }]
             .into(),
@@ -727,15 +1337,16 @@ mod test {
 
         // without mvcc
         expect.clear_debug_info();
-        assert_eq!(mock_txn_lock_not_found_err(false), expect);
+        assert_eq!(strip_trace(mock_txn_lock_not_found_err(false)), expect);
 
-        // with mvcc
+        // with mvcc, collection enabled
+        let _scope = MvccDebugInfoScope::enter(true);
         let mvcc_info = Some(mock_mvcc_info());
         expect.set_debug_info(expected_debug_info_from_mvcc(
             key.clone(),
             mvcc_info.clone().unwrap(),
         ));
-        assert_eq!(mock_txn_lock_not_found_err(true), expect);
+        assert_eq!(strip_trace(mock_txn_lock_not_found_err(true)), expect);
     }
 
     #[test]
@@ -763,14 +1374,103 @@ mod test {
 
         // without mvcc
         expect.clear_debug_info();
-        assert_eq!(mock_commit_ts_expired_err(false), expect);
+        assert_eq!(strip_trace(mock_commit_ts_expired_err(false)), expect);
 
-        // with mvcc
+        // with mvcc, collection enabled
+        let _scope = MvccDebugInfoScope::enter(true);
         let mvcc = Some(mock_mvcc_info());
         expect.set_debug_info(expected_debug_info_from_mvcc(
             key.clone(),
             mvcc.clone().unwrap(),
         ));
-        assert_eq!(mock_commit_ts_expired_err(true), expect);
+        assert_eq!(strip_trace(mock_commit_ts_expired_err(true)), expect);
+    }
+
+    #[test]
+    fn test_mvcc_debug_info_scope_gates_collection_and_is_off_by_default() {
+        let case = || {
+            extract_key_error(&Error::from(TxnError::from(MvccError::from(
+                MvccErrorInner::TxnLockNotFound {
+                    start_ts: TimeStamp::new(123),
+                    commit_ts: TimeStamp::new(456),
+                    key: b"key".to_vec(),
+                    mvcc_info: Some(mock_mvcc_info()),
+                },
+            ))))
+        };
+
+        // off by default: no scope entered.
+        assert!(case().debug_info.is_none());
+
+        {
+            let _scope = MvccDebugInfoScope::enter(true);
+            assert!(!case().debug_info.as_ref().unwrap().mvcc_info.is_empty());
+
+            {
+                let _inner_scope = MvccDebugInfoScope::enter(false);
+                assert!(case().debug_info.is_none());
+            }
+            // restored after the nested scope dropped.
+            assert!(!case().debug_info.as_ref().unwrap().mvcc_info.is_empty());
+        }
+        // restored after the outer scope dropped.
+        assert!(case().debug_info.is_none());
+    }
+
+    #[test]
+    fn test_mvcc_debug_info_truncates_writes_to_byte_budget() {
+        let _scope = MvccDebugInfoScope::enter(true);
+        // Comfortably more entries than the default 64KiB / 64B-per-entry
+        // budget allows, so truncation is exercised without touching the
+        // (process-wide) configured budget itself.
+        let writes = (1..=1_100u64)
+            .map(|ts| (TimeStamp::new(ts), Write::new(WriteType::Lock, ts.into(), None)))
+            .collect();
+        let mvcc_info = MvccInfo {
+            lock: None,
+            writes,
+            values: vec![],
+        };
+
+        let err = extract_key_error(&Error::from(TxnError::from(MvccError::from(
+            MvccErrorInner::TxnLockNotFound {
+                start_ts: TimeStamp::new(1),
+                commit_ts: TimeStamp::new(2),
+                key: b"key".to_vec(),
+                mvcc_info: Some(mvcc_info),
+            },
+        ))));
+
+        let debug_info = err.debug_info.as_ref().unwrap();
+        let mvcc_debug_info = &debug_info.mvcc_info[0];
+        assert!(mvcc_debug_info.truncated);
+        assert_eq!(mvcc_debug_info.total_writes, 1_100);
+        assert!(mvcc_debug_info.get_mvcc().writes.len() < 1_100);
+    }
+
+    #[test]
+    fn test_extract_kv_pairs_for_keys_attributes_batch_failure_to_every_key() {
+        let keys = vec![b"k1".to_vec(), b"k2".to_vec(), b"k3".to_vec()];
+        let res: Result<Vec<Result<KvPair>>> = Err(ErrorInner::DeadlineExceeded.into());
+
+        let pairs = extract_kv_pairs_for_keys(res, &keys);
+        assert_eq!(pairs.len(), keys.len());
+        for (pair, key) in pairs.iter().zip(&keys) {
+            assert_eq!(pair.get_key(), key.as_slice());
+            assert!(pair.get_error().get_abort().contains("batch failed"));
+        }
+    }
+
+    #[test]
+    fn test_extract_key_errors_for_keys_attributes_batch_failure_to_every_key() {
+        let keys = vec![b"k1".to_vec(), b"k2".to_vec()];
+        let res: Result<Vec<Result<()>>> =
+            Err(ErrorInner::component_busy(BusyComponent::Scheduler, None).into());
+
+        let errors = extract_key_errors_for_keys(res, &keys);
+        assert_eq!(errors.len(), keys.len());
+        for error in &errors {
+            assert!(error.get_abort().contains("batch failed"));
+        }
     }
 }